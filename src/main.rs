@@ -1,6 +1,6 @@
 fn main() {
     if let Err(err) = amem::run_cli() {
         eprintln!("error: {err:#}");
-        std::process::exit(1);
+        std::process::exit(amem::exit_code(&err));
     }
 }
@@ -0,0 +1,173 @@
+//! Colors of the rendered text output (`today`, `get diary`, `get acts`) and
+//! of the diffs printed by `set owner`: one palette per terminal background,
+//! chosen by `--theme`.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// `--theme` / `[output] theme`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Dark or light from `COLORFGBG`; dark when it is unset.
+    Auto,
+    Dark,
+    Light,
+    /// No colors, like `--color never`.
+    None,
+}
+
+/// `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and `NO_COLOR` is unset.
+    Auto,
+    Always,
+    Never,
+}
+
+/// SGR parameters of each styled part of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// `== Section ==` headers, drawn as `── Section ──`.
+    pub header: &'static str,
+    /// The `[path]` line under a header.
+    pub path: &'static str,
+    /// Entry times and timestamps.
+    pub time: &'static str,
+    /// `[source]` tags.
+    pub source: &'static str,
+    /// Added lines of a diff.
+    pub added: &'static str,
+    /// Removed lines of a diff.
+    pub removed: &'static str,
+    /// `@@` hunk headers of a diff.
+    pub hunk: &'static str,
+}
+
+pub const DARK: Palette = Palette {
+    header: "1;36",
+    path: "90",
+    time: "33",
+    source: "35",
+    added: "32",
+    removed: "31",
+    hunk: "36",
+};
+
+pub const LIGHT: Palette = Palette {
+    header: "1;34",
+    path: "2",
+    time: "31",
+    source: "35",
+    added: "32",
+    removed: "31",
+    hunk: "34",
+};
+
+impl Theme {
+    /// The palette to paint with; `None` for plain text. `colorfgbg` is the
+    /// value of `COLORFGBG`, consulted by `auto`.
+    pub fn palette(self, colorfgbg: Option<&str>) -> Option<Palette> {
+        match self {
+            Theme::Dark => Some(DARK),
+            Theme::Light => Some(LIGHT),
+            Theme::None => None,
+            Theme::Auto => match colorfgbg.and_then(background_is_light) {
+                Some(true) => Some(LIGHT),
+                _ => Some(DARK),
+            },
+        }
+    }
+}
+
+/// `COLORFGBG` is `fg;bg` (rxvt, Konsole) or `fg;default;bg`; background
+/// 7 (white) and 9-15 (bright colors) are light.
+fn background_is_light(colorfgbg: &str) -> Option<bool> {
+    let bg: u8 = colorfgbg.rsplit(';').next()?.trim().parse().ok()?;
+    Some(bg == 7 || (9..=15).contains(&bg))
+}
+
+/// `text` with headers, path lines, entry times, and source tags colored.
+/// Every other byte is kept as is.
+pub fn paint(text: &str, palette: &Palette) -> String {
+    let mut out = String::with_capacity(text.len() + text.len() / 4);
+    for line in text.split_inclusive('\n') {
+        let (body, newline) = match line.strip_suffix('\n') {
+            Some(body) => (body, "\n"),
+            None => (line, ""),
+        };
+        paint_line(&mut out, body, palette);
+        out.push_str(newline);
+    }
+    out
+}
+
+/// A unified diff with added, removed, and hunk header lines colored; the
+/// `---` / `+++` file headers stay plain.
+pub fn paint_diff(diff: &str, palette: &Palette) -> String {
+    let mut out = String::with_capacity(diff.len() + diff.len() / 4);
+    for line in diff.lines() {
+        let sgr = match line.as_bytes().first() {
+            _ if line.starts_with("+++") || line.starts_with("---") => None,
+            Some(b'+') => Some(palette.added),
+            Some(b'-') => Some(palette.removed),
+            Some(b'@') => Some(palette.hunk),
+            _ => None,
+        };
+        match sgr {
+            Some(sgr) => styled(&mut out, sgr, line),
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn styled(out: &mut String, sgr: &str, text: &str) {
+    out.push_str(&format!("\x1b[{sgr}m{text}\x1b[0m"));
+}
+
+fn paint_line(out: &mut String, line: &str, palette: &Palette) {
+    if let Some(rest) = line.strip_prefix("== ")
+        && let Some(end) = rest.find(" ==")
+    {
+        styled(out, palette.header, &format!("── {} ──", &rest[..end]));
+        out.push_str(&rest[end + 3..]);
+        return;
+    }
+    if line.starts_with('[') && line.ends_with(']') && !line.contains("] ") {
+        styled(out, palette.path, line);
+        return;
+    }
+    let Some(rest) = line.strip_prefix("- ") else {
+        out.push_str(line);
+        return;
+    };
+    out.push_str("- ");
+    let stamp_len = if rest.starts_with('[') {
+        rest.find(']').map(|end| end + 1)
+    } else {
+        let bytes = rest.as_bytes();
+        (bytes.len() >= 5
+            && bytes[2] == b':'
+            && [0, 1, 3, 4].iter().all(|&i| bytes[i].is_ascii_digit()))
+        .then_some(5)
+    };
+    let Some(stamp_len) = stamp_len else {
+        out.push_str(rest);
+        return;
+    };
+    styled(out, palette.time, &rest[..stamp_len]);
+    let rest = &rest[stamp_len..];
+    if let Some(tag) = rest.strip_prefix(" [")
+        && let Some(end) = tag.find(']')
+        && !tag[..end].contains(char::is_whitespace)
+    {
+        out.push(' ');
+        styled(out, palette.source, &format!("[{}]", &tag[..end]));
+        out.push_str(&tag[end + 1..]);
+    } else {
+        out.push_str(rest);
+    }
+}
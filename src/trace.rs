@@ -0,0 +1,175 @@
+//! Opt-in timing spans. With `AMEM_TRACE_FILE` set, each invocation appends
+//! one JSON line to that file: the command, the argument names (never their
+//! values), and the tree of spans with their total time. Unset, `span` is an
+//! atomic load and nothing else.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+pub const TRACE_FILE_ENV: &str = "AMEM_TRACE_FILE";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<State> = Mutex::new(State {
+    nodes: Vec::new(),
+    open: Vec::new(),
+});
+
+#[derive(Debug)]
+struct Node {
+    name: &'static str,
+    parent: Option<usize>,
+    count: u64,
+    us: u64,
+}
+
+/// Every span of the invocation; repeated spans under the same parent share
+/// one node, so a file read per day stays one entry with a count.
+#[derive(Debug)]
+struct State {
+    nodes: Vec<Node>,
+    open: Vec<usize>,
+}
+
+/// One line of the trace file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceLine {
+    pub at: String,
+    /// Subcommand path, e.g. `set diary`; empty for the bare `amem`.
+    pub command: String,
+    /// Names of the arguments given on the command line.
+    pub args: Vec<String>,
+    pub ok: bool,
+    pub total_us: u64,
+    pub spans: Vec<TraceSpan>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceSpan {
+    pub name: String,
+    /// Times the span was entered.
+    pub count: u64,
+    /// Time spent in it, over all entries.
+    pub us: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<TraceSpan>,
+}
+
+/// Starts recording when `AMEM_TRACE_FILE` is set; returns the file.
+pub fn start_from_env() -> Option<PathBuf> {
+    let path = std::env::var_os(TRACE_FILE_ENV).filter(|path| !path.is_empty())?;
+    ENABLED.store(true, Ordering::Relaxed);
+    Some(PathBuf::from(path))
+}
+
+/// Times the scope it lives in, under the innermost open span.
+#[must_use = "a span times the scope its guard lives in"]
+pub struct Span(Option<(usize, Instant)>);
+
+pub fn span(name: &'static str) -> Span {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Span(None);
+    }
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let parent = state.open.last().copied();
+    let index = match state
+        .nodes
+        .iter()
+        .position(|node| node.parent == parent && node.name == name)
+    {
+        Some(index) => index,
+        None => {
+            state.nodes.push(Node {
+                name,
+                parent,
+                count: 0,
+                us: 0,
+            });
+            state.nodes.len() - 1
+        }
+    };
+    state.nodes[index].count += 1;
+    state.open.push(index);
+    Span(Some((index, Instant::now())))
+}
+
+/// `f()` timed as a span named `name`.
+pub fn timed<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    let _span = span(name);
+    f()
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some((index, started)) = self.0 else {
+            return;
+        };
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        state.nodes[index].us += started.elapsed().as_micros() as u64;
+        if let Some(at) = state.open.iter().rposition(|&open| open == index) {
+            state.open.truncate(at);
+        }
+    }
+}
+
+fn tree(nodes: &[Node], parent: Option<usize>) -> Vec<TraceSpan> {
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| node.parent == parent)
+        .map(|(index, node)| TraceSpan {
+            name: node.name.to_string(),
+            count: node.count,
+            us: node.us,
+            spans: tree(nodes, Some(index)),
+        })
+        .collect()
+}
+
+/// The spans recorded so far as a line for `path`, appended to it.
+pub fn finish(
+    path: &Path,
+    at: String,
+    command: String,
+    args: Vec<String>,
+    ok: bool,
+    total_us: u64,
+) -> std::io::Result<()> {
+    let spans = {
+        let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        tree(&state.nodes, None)
+    };
+    let line = TraceLine {
+        at,
+        command,
+        args,
+        ok,
+        total_us,
+        spans,
+    };
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut out = serde_json::to_string(&line).map_err(std::io::Error::other)?;
+    out.push('\n');
+    file.write_all(out.as_bytes())
+}
+
+/// Total time per span name in one line, nested spans included.
+pub fn span_totals(line: &TraceLine) -> Vec<(String, u64)> {
+    fn walk(spans: &[TraceSpan], totals: &mut Vec<(String, u64)>) {
+        for span in spans {
+            match totals.iter_mut().find(|(name, _)| *name == span.name) {
+                Some((_, us)) => *us += span.us,
+                None => totals.push((span.name.clone(), span.us)),
+            }
+            walk(&span.spans, totals);
+        }
+    }
+    let mut totals = Vec::new();
+    walk(&line.spans, &mut totals);
+    totals
+}
@@ -0,0 +1,931 @@
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Output types whose JSON shape is part of the public CLI contract, keyed by
+/// the name accepted by `amem schema <type>`.
+pub const SCHEMA_NAMES: &[(&str, &str)] = &[
+    ("today", "TodayJson"),
+    ("search_hit", "SearchHit"),
+    ("search_status", "SearchStatusJson"),
+    ("task", "TaskEntry"),
+    ("activity", "ActivityEntry"),
+    ("diary", "DiaryEntry"),
+    ("decision", "DecisionEntry"),
+    ("plan", "PlanJson"),
+    ("goal", "GoalEntry"),
+    ("timeline", "TimelineEntry"),
+    ("init", "InitJson"),
+    ("keep", "KeepJson"),
+    ("log", "LogJson"),
+    ("error", "ErrorJson"),
+    ("version", "VersionJson"),
+    ("doctor", "DoctorJson"),
+    ("index_record", "IndexRecord"),
+    ("trash", "TrashEntry"),
+    ("stats", "StatsJson"),
+    ("stats_hourly", "HourlyStatsJson"),
+    ("stats_trace", "TraceStatsJson"),
+    ("audit", "AuditEntry"),
+    ("seal", "SealJson"),
+    ("assistant_launch", "AssistantLaunchJson"),
+    ("diary_block", "DiaryBlockJson"),
+    ("merge_suggestions", "MergeSuggestionsJson"),
+    ("explain", "ExplainJson"),
+];
+
+pub fn schema_by_name(name: &str) -> Option<Schema> {
+    let normalized = name.trim().to_ascii_lowercase().replace('-', "_");
+    let schema = match normalized.as_str() {
+        "today" | "todayjson" => reported_schema::<TodayJson>(),
+        "search_hit" | "searchhit" | "search" => schema_for!(SearchHit),
+        "search_status" | "searchstatusjson" => reported_schema::<SearchStatusJson>(),
+        "task" | "tasks" | "taskentry" => schema_for!(TaskEntry),
+        "activity" | "acts" | "activityentry" => schema_for!(ActivityEntry),
+        "diary" | "diaryentry" => schema_for!(DiaryEntry),
+        "decision" | "decisions" | "decisionentry" => schema_for!(DecisionEntry),
+        "plan" | "planjson" => reported_schema::<PlanJson>(),
+        "goal" | "goals" | "goalentry" => schema_for!(GoalEntry),
+        "timeline" | "timelineentry" => schema_for!(TimelineEntry),
+        "init" | "initjson" => reported_schema::<InitJson>(),
+        "keep" | "keepjson" => reported_schema::<KeepJson>(),
+        "log" | "logjson" => reported_schema::<LogJson>(),
+        "error" | "errorjson" => schema_for!(ErrorJson),
+        "version" | "versionjson" => reported_schema::<VersionJson>(),
+        "doctor" | "doctorjson" => reported_schema::<DoctorJson>(),
+        "index_record" | "indexrecord" => schema_for!(IndexRecord),
+        "trash" | "trash_entry" | "trashentry" => schema_for!(TrashEntry),
+        "stats" | "statsjson" => reported_schema::<StatsJson>(),
+        "stats_hourly" | "hourly" | "hourlystatsjson" => reported_schema::<HourlyStatsJson>(),
+        "stats_trace" | "trace" | "tracestatsjson" => reported_schema::<TraceStatsJson>(),
+        "audit" | "audit_entry" | "auditentry" => schema_for!(AuditEntry),
+        "seal" | "sealjson" => reported_schema::<SealJson>(),
+        "assistant_launch" | "assistantlaunch" => reported_schema::<AssistantLaunchJson>(),
+        "diary_block" | "diaryblock" => reported_schema::<DiaryBlockJson>(),
+        "merge_suggestions" | "mergesuggestionsjson" => reported_schema::<MergeSuggestionsJson>(),
+        "explain" | "explainjson" => reported_schema::<ExplainJson>(),
+        _ => return None,
+    };
+    Some(schema)
+}
+
+/// Schema of an object output as `emit_json` prints it: `T`'s own fields
+/// plus the optional [`Diagnostics`] lists, and nothing else.
+fn reported_schema<T: JsonSchema>() -> Schema {
+    let inner = schema_for!(T);
+    let mut schema = schema_for!(Reported<T>);
+    for key in ["title", "description"] {
+        match inner.get(key) {
+            Some(value) => schema.insert(key.to_string(), value.clone()),
+            None => schema.remove(key),
+        };
+    }
+    schema.insert("additionalProperties".to_string(), false.into());
+    schema
+}
+
+/// `amem search --index-status`: the hits plus how current the index is.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchStatusJson {
+    /// `null` when the memory dir has no index.
+    pub index: Option<IndexStatus>,
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IndexStatus {
+    /// When the newest chunk was written (RFC 3339), `null` for an empty index.
+    pub built_at: Option<String>,
+    pub files_indexed: usize,
+    /// Some hit's file changed since it was indexed.
+    pub stale: bool,
+    /// The hit paths whose content no longer matches the index.
+    pub stale_paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchHit {
+    pub path: String,
+    pub score: f64,
+    pub snippet: String,
+    /// Memory root the hit came from (set by `amem search`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_dir: Option<String>,
+    /// 1-based line of the snippet (`amem search --all-matches`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// 0-based paragraph of the file the hit came from (`--all-matches`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_index: Option<usize>,
+    /// How the score was computed (`amem search --explain`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explain: Option<SearchExplain>,
+    /// Days between the hit's date and `context --date` (negative: before).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days_from_anchor: Option<i64>,
+}
+
+/// Breakdown of a hit's score: the term scores plus the phrase bonus add up
+/// to `SearchHit::score`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchExplain {
+    /// `index` or `files`.
+    pub backend: String,
+    pub terms: Vec<TermScore>,
+    /// Bonus for containing the whole query verbatim (0 when it did not apply).
+    pub phrase_bonus: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TermScore {
+    pub token: String,
+    pub tf: i64,
+    pub df: i64,
+    pub idf: f64,
+    /// `tf * idf`.
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TodayJson {
+    pub date: String,
+    /// Current local time (RFC 3339), independent of `date`.
+    pub now: String,
+    /// English weekday name of `date`.
+    pub weekday: String,
+    /// Days from the latest recent diary entry to `date`; null when the
+    /// recent sections hold none.
+    pub days_since_last_diary: Option<i64>,
+    /// Days from the latest recent activity entry to `date`; null when the
+    /// recent sections hold none.
+    pub days_since_last_activity: Option<i64>,
+    pub agent_identity: String,
+    pub agent_identity_path: String,
+    pub agent_identity_meta: AgentIdentityMeta,
+    pub agent_soul: String,
+    pub agent_soul_path: String,
+    pub owner_profile: String,
+    pub owner_profile_path: String,
+    pub owner_preferences: String,
+    pub owner_preferences_path: String,
+    pub owner_diary: String,
+    pub owner_diary_path: String,
+    pub owner_diary_paths: Vec<String>,
+    pub owner_diary_recent: Vec<RecentDailySection>,
+    /// Body of the owner's plan for the ISO week containing `date`.
+    pub plan: String,
+    pub plan_path: String,
+    pub open_tasks: String,
+    pub open_tasks_paths: Vec<String>,
+    pub activity: String,
+    pub activity_paths: Vec<String>,
+    pub activity_recent: Vec<RecentDailySection>,
+    pub agent_memories: String,
+    pub agent_memories_paths: Vec<String>,
+    /// P1-P3 memories surfaced because an open task matches them.
+    pub agent_memories_active: Vec<ActiveMemory>,
+    /// Decisions recorded in the calendar week of `date`, newest first.
+    pub decisions_week: Vec<DecisionEntry>,
+    /// Tasks of `done.md` completed in the `[snapshot] done_window_days`
+    /// days ending at `date`, newest first, at most 10.
+    pub done_tasks_recent: Vec<RecentDoneTask>,
+    /// Activity lines the rendered snapshot leaves out because the diary of
+    /// the same day holds the same text (`[snapshot] dedup_across_sections`).
+    pub deduped_entries: usize,
+    /// When each assistant last ran through `amem <assistant>` (RFC 3339).
+    pub assistants_last_used: BTreeMap<String, String>,
+}
+
+/// Display metadata from the frontmatter of `agent/IDENTITY.md`; every field
+/// is null when the file has none.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AgentIdentityMeta {
+    pub display_name: Option<String>,
+    pub emoji: Option<String>,
+    pub short_bio: Option<String>,
+}
+
+/// The last `amem <assistant>` launch of one assistant, kept in
+/// `.index/assistants.json` and shown by `amem sessions status`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AssistantUse {
+    /// Last seed prompt (RFC 3339).
+    pub last_seed: Option<String>,
+    /// Last interactive session, seeded or resumed (RFC 3339).
+    pub last_resume: Option<String>,
+    /// Working directory of the last session.
+    pub cwd: String,
+    /// Exit code of the last interactive session; `null` when a signal ended it.
+    pub exit_code: Option<i32>,
+}
+
+/// A memory brought into the snapshot by an open task; its file stays put.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ActiveMemory {
+    pub priority: String,
+    pub filename: String,
+    pub path: String,
+    pub content: String,
+    /// Text of the open task that matched.
+    pub task: String,
+    /// Terms the task shares with the memory's filename and first line.
+    pub score: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RecentDailySection {
+    pub date: String,
+    pub paths: Vec<String>,
+    pub content: String,
+}
+
+/// `amem log`: one object per part that was asked for.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LogJson {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diary: Option<LogPart>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<LogPart>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<LogPart>,
+}
+
+/// One write of `amem log`. A failed part leaves the others in place.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LogPart {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The line as written to the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<String>,
+    /// Entry id (`entry_ids`) or task hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct KeepJson {
+    pub path: String,
+    pub source: String,
+    /// Id stamped on the new line when `entry_ids` is enabled, or the hash
+    /// of a `task-note`.
+    pub id: Option<String>,
+    /// The line as written to the file (`--print-content`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appended_line: Option<String>,
+    /// Entries in the file after the append (`--print-content`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_count: Option<usize>,
+    /// Characters read from the clipboard (`capture --clipboard`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chars: Option<usize>,
+    /// The day so far, for entries written to a diary or activity file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_summary: Option<DailySummaryJson>,
+}
+
+/// Summary of the day a diary or activity entry was just written to.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DailySummaryJson {
+    pub date: String,
+    pub summary: String,
+    /// True when `summary` was derived from the day's entries for this reply
+    /// only; the file's frontmatter keeps its own (empty for today).
+    pub ephemeral: bool,
+    /// Entries of the day after the write.
+    pub entry_count: usize,
+}
+
+/// Output of `set diary --multi` and `keep --kind diary --multi`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DiaryBlockJson {
+    pub path: String,
+    pub date: String,
+    /// The entries appended, in the order of the block.
+    pub entries: Vec<DiaryBlockEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_summary: Option<DailySummaryJson>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DiaryBlockEntry {
+    /// HH:MM from the line, or the current time for a leading untimed line.
+    pub time: String,
+    /// Entry text; continuation lines follow `\n  `.
+    pub text: String,
+    /// Id stamped on the line when `entry_ids` is enabled.
+    pub id: Option<String>,
+    /// The line as written to the file (`--print-content`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appended_line: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InitJson {
+    pub memory_dir: String,
+    pub created: Vec<String>,
+    /// Profile after `init --interactive` / `--answers-file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<InitProfileJson>,
+    /// Result of `init --adopt`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adopt: Option<InitAdoptJson>,
+}
+
+/// Notes copied (or moved) into the memory dir by `init --adopt`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InitAdoptJson {
+    /// `diary` or `activity`.
+    pub kind: String,
+    /// Files written to a daily file that did not exist yet.
+    pub adopted: usize,
+    /// Files merged into an existing daily file.
+    pub merged: usize,
+    pub skipped: usize,
+    pub files: Vec<InitAdoptFileJson>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InitAdoptFileJson {
+    pub source: String,
+    /// `adopted`, `merged`, or `skipped`.
+    pub status: String,
+    /// Daily file written, relative to the memory root.
+    pub path: Option<String>,
+    pub date: Option<String>,
+    /// Lines added; lines already in the daily file are not counted.
+    pub lines: usize,
+    /// Why a file was skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InitProfileJson {
+    pub name: Option<String>,
+    pub what_to_call_them: Option<String>,
+    pub timezone: Option<String>,
+    pub language: Option<String>,
+    pub github_username: Option<String>,
+    /// Statement under the heading of `agent/IDENTITY.md`.
+    pub identity: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ActivityEntry {
+    pub timestamp: String,
+    pub source: Option<String>,
+    /// Project of the entry (`[proj:name]`), if tagged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub text: String,
+    pub path: String,
+    /// `[#id]` stamped on the line, if any.
+    pub id: Option<String>,
+    /// Exit status of a `run` entry (`[exit:N]`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Duration of a `run` entry in seconds (`[dur:Ns]`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u64>,
+}
+
+/// A recently completed task of the `today` snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RecentDoneTask {
+    /// When the task was marked done (`yyyy-mm-dd HH:MM`), or when it was
+    /// added for legacy lines without a `[done ...]` timestamp.
+    pub timestamp: String,
+    /// `timestamp` is the creation time, not the completion time.
+    pub approximate: bool,
+    pub hash: Option<String>,
+    pub text: String,
+}
+
+/// One `keep --kind decision` line of `agent/decisions/`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DecisionEntry {
+    pub timestamp: String,
+    pub source: Option<String>,
+    /// What was decided.
+    pub text: String,
+    pub rationale: String,
+    /// What was considered and not chosen, if recorded.
+    pub alternatives: Option<String>,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DiaryEntry {
+    pub timestamp: String,
+    pub text: String,
+    pub path: String,
+    /// `[#id]` stamped on the line, if any.
+    pub id: Option<String>,
+    /// Absolute paths of the files its `[att:...]` tokens reference.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<String>,
+}
+
+/// One goal of `owner/goals/yyyy-Www.md` and the tasks tagged with its hash.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GoalEntry {
+    /// ISO week, `yyyy-Www`.
+    pub week: String,
+    pub hash: String,
+    pub timestamp: Option<String>,
+    pub text: String,
+    pub open_tasks: usize,
+    pub done_tasks: usize,
+    /// Done share of the linked tasks, rounded down; 0 without linked tasks.
+    pub percent: u32,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PlanJson {
+    /// ISO week, `yyyy-Www`.
+    pub week: String,
+    pub path: String,
+    pub summary: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TaskEntry {
+    pub status: String,
+    pub timestamp: Option<String>,
+    pub hash: Option<String>,
+    /// When the task was marked done (`yyyy-mm-dd HH:MM`), if recorded.
+    pub done_at: Option<String>,
+    /// Whole days since an open task was added; `None` for done tasks and
+    /// tasks without a timestamp.
+    pub age_days: Option<i64>,
+    /// Hash of the goal the task is linked to by a `[goal:<hash>]` tag.
+    pub goal: Option<String>,
+    pub text: String,
+    #[serde(skip)]
+    pub(crate) raw_line: String,
+    #[serde(skip)]
+    pub(crate) line_index: usize,
+    #[serde(skip)]
+    pub(crate) source_path: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineKind {
+    Diary,
+    Activity,
+    Inbox,
+    TaskAdded,
+    TaskDone,
+}
+
+impl TimelineKind {
+    /// Short tag used in the text rendering of `amem get timeline`.
+    pub fn label(self) -> &'static str {
+        match self {
+            TimelineKind::Diary => "diary",
+            TimelineKind::Activity => "activity",
+            TimelineKind::Inbox => "inbox",
+            TimelineKind::TaskAdded => "task+",
+            TimelineKind::TaskDone => "task✓",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TimelineEntry {
+    pub timestamp: String,
+    pub kind: TimelineKind,
+    pub source: Option<String>,
+    pub text: String,
+    pub path: String,
+}
+
+/// A memory file skipped because it is larger than `limits.max_file_bytes`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OversizeFile {
+    pub path: String,
+    pub bytes: u64,
+    pub limit: u64,
+}
+
+/// One protected file compared with `.integrity.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct IntegrityCheck {
+    pub path: String,
+    /// `ok`, `changed`, `missing`, `unsealed` (protected but not in the
+    /// seal), or `bad_signature` (for `.integrity.json` itself).
+    pub status: String,
+    /// SHA-256 recorded by the last seal; `-` for a file absent then.
+    pub sealed_sha256: Option<String>,
+    /// SHA-256 of the file now; null when it does not exist.
+    pub sha256: Option<String>,
+}
+
+/// Output of `amem triage suggest-merges`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MergeSuggestionsJson {
+    /// False for a dry run.
+    pub applied: bool,
+    pub threshold: f64,
+    pub groups: Vec<MergeGroup>,
+}
+
+/// Memories similar enough to merge into one.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MergeGroup {
+    /// The highest-priority member, which the others merge into.
+    pub target: String,
+    /// The target first, then the rest by priority and filename.
+    pub members: Vec<MergeMember>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MergeMember {
+    pub path: String,
+    pub filename: String,
+    pub priority: String,
+    pub first_line: String,
+    /// Share of terms in common with the target, from 0.0 to 1.0.
+    pub similarity: f64,
+    /// Where `--apply` put the merged file in `.trash/`.
+    pub trash_id: Option<String>,
+}
+
+/// Output of `amem explain <path>`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ExplainJson {
+    /// Path relative to the memory dir.
+    pub path: String,
+    pub exists: bool,
+    /// Kind as listed by `amem list` (`other` for an unmanaged file).
+    pub kind: String,
+    /// What amem keeps in the file; `unmanaged file` when amem does not use it.
+    pub role: String,
+    /// Day of a dated diary/activity/decision file (Monday of a weekly one).
+    pub date: Option<String>,
+    /// `summary:` of the frontmatter.
+    pub summary: Option<String>,
+    pub entry_count: usize,
+    pub first_entry: Option<String>,
+    pub last_entry: Option<String>,
+    /// Commands that read or write the file, with example invocations.
+    pub commands: Vec<ExplainCommand>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ExplainCommand {
+    pub command: String,
+    pub description: String,
+}
+
+/// Output of `amem seal` and `amem seal --verify`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SealJson {
+    pub path: String,
+    /// When the seal was last written (RFC 3339); null before the first seal.
+    pub sealed_at: Option<String>,
+    /// True when every check is `ok`.
+    pub ok: bool,
+    pub checks: Vec<IntegrityCheck>,
+}
+
+/// A memory file skipped because it could not be read.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReadWarning {
+    pub path: String,
+    pub error: String,
+}
+
+/// An object output with the problems met while producing it; `emit_json`
+/// adds these next to the output's own fields, and each list is omitted
+/// when empty.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Reported<T> {
+    #[serde(flatten)]
+    pub output: T,
+    #[serde(flatten)]
+    pub diagnostics: Diagnostics,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Diagnostics {
+    /// Memory files skipped because they could not be read.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ReadWarning>,
+    /// Memory files skipped for exceeding `limits.max_file_bytes`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_oversize: Vec<OversizeFile>,
+    /// Protected files that no longer match `.integrity.json`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub integrity_warnings: Vec<IntegrityCheck>,
+}
+
+impl Diagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+            && self.skipped_oversize.is_empty()
+            && self.integrity_warnings.is_empty()
+    }
+}
+
+/// Category of a failed command, stable across releases for scripting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    General,
+    NotFound,
+    PermissionDenied,
+    InvalidInput,
+    /// An assistant's seed call failed or did not reply `MEMORY_READY`.
+    AssistantSeedFailed,
+    /// The interactive assistant session exited non-zero.
+    AssistantResumeFailed,
+    /// The assistant CLI is not installed (or not on `PATH`).
+    BinaryNotFound,
+    /// The seed call succeeded but its output named no session to resume.
+    SessionIdMissing,
+}
+
+/// Printed to stdout instead of normal output when a `--json` command fails.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorJson {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// The assistant (`codex`, `gemini`, ...) whose CLI failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assistant: Option<String>,
+    /// Its exit status, when it ran and exited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<i32>,
+    /// Last 2 KB of its stdout (empty for the interactive session).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_tail: Option<String>,
+    /// Last 2 KB of its stderr (empty for the interactive session).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_tail: Option<String>,
+}
+
+/// Printed by an assistant command under `--json` once its session ends.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AssistantLaunchJson {
+    pub assistant: String,
+    /// Session the seed call created; null when none was seeded.
+    pub session_id: Option<String>,
+    /// `seeded` (resumed the seed session), `resume_last` (`--resume-only`),
+    /// or `fresh` (a plain launch: the memory dir was empty).
+    pub mode: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VersionJson {
+    pub binary_version: String,
+    /// Memory dir format this binary reads and writes.
+    pub format_version: u32,
+    /// Format stamped in the memory dir, if any.
+    pub memory_format_version: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Error,
+}
+
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl DoctorCheck {
+    pub fn ok(name: &str, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Ok, detail)
+    }
+
+    pub fn warn(name: &str, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Warn, detail)
+    }
+
+    pub fn error(name: &str, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Error, detail)
+    }
+
+    fn new(name: &str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        DoctorCheck {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DoctorJson {
+    pub memory_dir: String,
+    pub checks: Vec<DoctorCheck>,
+    /// Files rewritten by `amem doctor --fix`.
+    #[serde(default)]
+    pub fixed: Vec<String>,
+}
+
+/// One line of `amem index export --ndjson`: each file record, then its chunks.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "record", rename_all = "snake_case", deny_unknown_fields)]
+pub enum IndexRecord {
+    File {
+        path: String,
+        content_hash: String,
+        mtime: i64,
+    },
+    Chunk {
+        path: String,
+        /// Memory area of `path` (`diary`, `activity`, `memory`, ...).
+        kind: String,
+        line_start: i64,
+        line_end: i64,
+        text: String,
+        /// Unigram token -> term frequency within the chunk.
+        tokens: BTreeMap<String, i64>,
+    },
+}
+
+/// An item in `.trash/`: a moved-away file or a removed daily line.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TrashEntry {
+    pub id: String,
+    /// `file` or `line`.
+    pub kind: String,
+    /// Original path relative to the memory root.
+    pub path: String,
+    pub deleted_at: String,
+    pub bytes: u64,
+    /// The removed line, for `line` entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<String>,
+    /// Body line index the removed line was at, for `line` entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_index: Option<usize>,
+}
+
+/// A line of `owner/.audit.md`: one change to an owner file.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AuditEntry {
+    /// Local time, `yyyy-mm-dd HH:MM:SS`.
+    pub timestamp: String,
+    /// e.g. `set owner`, `set diary --edit`.
+    pub command: String,
+    /// Profile key, `preference:<key>`, or the diary date.
+    pub target: String,
+    /// Changed file relative to the memory root.
+    pub path: String,
+    /// SHA-256 of the file after the change (`-` once it no longer exists).
+    pub sha256: String,
+    /// `--source` or `AMEM_SOURCE` of the invocation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Output of `amem stats --hourly`: activity and diary entries by hour.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HourlyStatsJson {
+    pub period: Option<String>,
+    /// Activity entries per hour of day, index 0 to 23.
+    pub activity: Vec<usize>,
+    /// Diary entries per hour of day, index 0 to 23.
+    pub diary: Vec<usize>,
+    /// Activity entries without a time prefix (parsed as `00:00`, not counted
+    /// in hour 0).
+    pub activity_unknown: usize,
+    /// Diary entries without a time prefix.
+    pub diary_unknown: usize,
+}
+
+/// Output of `amem stats --trace`: span timings over the invocations
+/// recorded in an `AMEM_TRACE_FILE`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TraceStatsJson {
+    pub path: String,
+    pub invocations: usize,
+    /// Lines that are not trace records.
+    pub skipped: usize,
+    /// Whole invocations.
+    pub total: SpanTimings,
+    /// Each span name, its nested occurrences summed per invocation, in
+    /// order of first appearance.
+    pub spans: Vec<SpanTimings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SpanTimings {
+    pub name: String,
+    /// Invocations that entered the span.
+    pub count: usize,
+    pub p50_us: u64,
+    pub p95_us: u64,
+}
+
+/// Output of `amem stats`: `run` entries rolled up per source.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StatsJson {
+    pub period: Option<String>,
+    pub sources: Vec<RunStats>,
+    /// Activity entries per `[proj:name]`, most first; untagged entries
+    /// count under `(none)`.
+    pub projects: Vec<ProjectStats>,
+    /// Files under `owner/attachments/`, whatever the period.
+    pub attachments: AttachmentStats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectStats {
+    pub project: String,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AttachmentStats {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RunStats {
+    pub source: String,
+    pub runs: usize,
+    pub failed: usize,
+    /// Share of runs that exited with 0, from 0.0 to 1.0.
+    pub success_rate: f64,
+    pub total_duration_secs: u64,
+}